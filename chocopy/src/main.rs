@@ -91,6 +91,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     opts.optflag("a", "ast", "Print bare AST");
     opts.optflag("t", "typed", "Print typed AST");
     opts.optflag("o", "obj", "Output object file without linking");
+    opts.optmulti(
+        "l",
+        "link-lib",
+        "Link an external C library against the output binary (repeatable). NOTE: there is no \
+         TypePy-side syntax yet to declare or call an extern function — this only wires the \
+         linker flag through; see typepy-stdlib's ffi.rs for the runtime-side marshalling half.",
+        "LIB",
+    );
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -139,7 +147,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let no_link = matches.opt_present("o");
     let static_lib = matches.opt_present("s");
-    codegen::codegen(input, ast, output, no_link, static_lib, PLATFORM)?;
+    // NOT YET END-TO-END: there is no frontend grammar, typecheck rule, or
+    // codegen lowering anywhere in this tree for declaring or calling an
+    // `extern` function — `frontend::process`/`typecheck::check` above have
+    // no notion of one. `link_libs` only threads `--link-lib`'s linker
+    // flags through to the existing link step; `typepy-stdlib`'s `ffi.rs`
+    // only provides the runtime-side marshalling those calls would need.
+    // Until a TypePy program can actually spell an extern declaration, this
+    // flag has nothing to link against, so warn instead of silently
+    // linking a library the compiled program can never call into.
+    let link_libs = matches.opt_strs("link-lib");
+    if !link_libs.is_empty() {
+        eprintln!(
+            "warning: --link-lib has no effect yet — TypePy has no `extern` declaration \
+             syntax, so no compiled program can call into a linked library"
+        );
+    }
+    codegen::codegen(input, ast, output, no_link, static_lib, link_libs, PLATFORM)?;
 
     Ok(())
 }
@@ -0,0 +1,249 @@
+use super::*;
+
+/// Number of power-of-two size classes, measured in `AllocUnit`s. Anything
+/// larger than the biggest class goes through the large-object path (a
+/// direct per-object allocation, same as before this module existed).
+const NUM_SIZE_CLASSES: usize = 9;
+const SIZE_CLASSES: [usize; NUM_SIZE_CLASSES] = [1, 2, 4, 8, 16, 32, 64, 128, 256];
+/// Number of blocks carved out of a freshly committed slab for a size class.
+const SLAB_BLOCKS: usize = 64;
+
+/// An intrusive free-list node. Lives inside the body of a dead object —
+/// once an object is unreachable its storage is fair game, so the first
+/// `AllocUnit` is reused to link the class's free list instead of being
+/// dropped and re-requested from the global allocator.
+#[repr(C)]
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+// Per-size-class free list heads.
+static FREE_LISTS: StaticCell<[Cell<Option<NonNull<FreeBlock>>>; NUM_SIZE_CLASSES]> =
+    StaticCell::new([const { Cell::new(None) }; NUM_SIZE_CLASSES]);
+
+/// One committed slab: `SLAB_BLOCKS` contiguous `class`-sized blocks
+/// obtained from a single `alloc_block` call. Tracked (alongside the free
+/// list) so `compact::compact` can slide a slab's survivors down to a
+/// contiguous prefix and release the slab entirely once it empties out.
+struct SlabInfo {
+    base: NonNull<AllocUnit>,
+    block_count: usize,
+}
+
+// Per-size-class slab tables, in commit order.
+static SLABS: StaticCell<[RefCell<Vec<SlabInfo>>; NUM_SIZE_CLASSES]> =
+    StaticCell::new([const { RefCell::new(Vec::new()) }; NUM_SIZE_CLASSES]);
+
+/// Returns the size class index covering `units`, or `None` if `units`
+/// exceeds the largest class and should go through the large-object path.
+fn size_class_for(units: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|&class_size| units <= class_size)
+}
+
+/// Commits a new slab for `class` and threads its blocks onto the class's
+/// free list. The slab is tracked in `SLABS` so a later `compact::compact`
+/// pass can slide its survivors down and release it if it ever empties
+/// out — it is never otherwise freed back to the global allocator.
+fn grow_slab(class: usize) {
+    let block_units = SIZE_CLASSES[class];
+    let slab_units = block_units * SLAB_BLOCKS;
+    let slab = alloc_block(slab_units);
+
+    SLABS.with(|slabs| {
+        slabs.borrow_mut().push(SlabInfo {
+            base: NonNull::new(slab).expect("alloc_block never returns null"),
+            block_count: SLAB_BLOCKS,
+        })
+    });
+
+    FREE_LISTS.with(|free_lists| {
+        let head = &free_lists[class];
+        for block_index in 0..SLAB_BLOCKS {
+            let block = unsafe { slab.add(block_index * block_units) } as *mut FreeBlock;
+            let next = head.get();
+            unsafe { block.write(FreeBlock { next }) };
+            head.set(NonNull::new(block));
+        }
+    });
+}
+
+/// Allocates a block of at least `units` `AllocUnit`s, returning raw,
+/// uninitialized memory together with the number of `AllocUnit`s newly
+/// committed from the global allocator (zero if an existing free-list
+/// block was reused). Callers use the committed count, not `units`, to
+/// track space for GC threshold accounting.
+pub(crate) fn alloc_units(units: usize) -> (*mut AllocUnit, usize) {
+    let Some(class) = size_class_for(units) else {
+        // Large-object path: one direct allocation per object.
+        return (alloc_block(units), units);
+    };
+
+    let committed = FREE_LISTS.with(|free_lists| {
+        if free_lists[class].get().is_none() {
+            grow_slab(class);
+            SIZE_CLASSES[class] * SLAB_BLOCKS
+        } else {
+            0
+        }
+    });
+
+    let pointer = FREE_LISTS.with(|free_lists| {
+        let head = &free_lists[class];
+        let block = head.get().expect("slab was just grown if its free list was empty");
+        head.set(unsafe { block.as_ref() }.next);
+        block.as_ptr() as *mut AllocUnit
+    });
+
+    (pointer, committed)
+}
+
+/// Returns a block previously obtained from `alloc_units` to the allocator.
+/// A class-sized block is spliced back onto its free list (a pointer
+/// write, no call into the global allocator); a large object is dropped
+/// outright. Returns the number of `AllocUnit`s actually released back to
+/// the global allocator (zero for class-sized blocks, since those stay
+/// committed for reuse).
+///
+/// # Safety
+/// - `pointer` and `units` must match a prior `alloc_units` call exactly.
+pub(crate) unsafe fn free_units(pointer: *mut AllocUnit, units: usize) -> usize {
+    unsafe {
+        let Some(class) = size_class_for(units) else {
+            dealloc_block(pointer, units);
+            return units;
+        };
+
+        FREE_LISTS.with(|free_lists| {
+            let head = &free_lists[class];
+            let block = pointer as *mut FreeBlock;
+            block.write(FreeBlock { next: head.get() });
+            head.set(NonNull::new(block));
+        });
+
+        0
+    }
+}
+
+/// Number of size classes, for `compact::compact` to iterate over.
+pub(crate) fn class_count() -> usize {
+    NUM_SIZE_CLASSES
+}
+
+/// The block size (in `AllocUnit`s) of `class`.
+pub(crate) fn class_block_units(class: usize) -> usize {
+    SIZE_CLASSES[class]
+}
+
+/// A snapshot of `class`'s committed slabs, in commit order, as
+/// `(base, block_count)` pairs.
+pub(crate) fn slab_snapshot(class: usize) -> Vec<(NonNull<AllocUnit>, usize)> {
+    SLABS.with(|slabs| {
+        slabs
+            .borrow()
+            .iter()
+            .map(|slab| (slab.base, slab.block_count))
+            .collect()
+    })
+}
+
+/// Replaces `class`'s slab table and free list after a compaction pass:
+/// `slabs` gives each surviving slab's base and new live-block count
+/// (every `compact::compact` caller passes these in the same commit order
+/// `slab_snapshot` returned them in, so `base` addresses are still the
+/// pre-move slab bases — only the objects within each slab moved).
+/// A slab with zero live blocks is released back to the global allocator
+/// entirely; a slab with `live_count < block_count` is kept; the blocks at
+/// `live_count..block_count` (the tail the survivors were slid out of) are
+/// threaded back onto the free list. Returns the number of `AllocUnit`s
+/// released back to the global allocator.
+///
+/// # Safety
+/// - `slabs` must be exactly `slab_snapshot(class)`'s slabs (same bases,
+///   same original `block_count`s), with each `live_count` no greater than
+///   the matching original `block_count`.
+pub(crate) unsafe fn rebuild_after_compaction(
+    class: usize,
+    slabs: &[(NonNull<AllocUnit>, usize)],
+) -> usize {
+    unsafe {
+        let block_units = SIZE_CLASSES[class];
+        let mut released = 0;
+
+        FREE_LISTS.with(|free_lists| free_lists[class].set(None));
+        SLABS.with(|tracked| tracked.borrow_mut().clear());
+
+        for &(base, live_count) in slabs {
+            if live_count == 0 {
+                dealloc_block(base.as_ptr(), block_units * SLAB_BLOCKS);
+                released += block_units * SLAB_BLOCKS;
+                continue;
+            }
+
+            SLABS.with(|tracked| {
+                tracked.borrow_mut().push(SlabInfo {
+                    base,
+                    block_count: SLAB_BLOCKS,
+                })
+            });
+            FREE_LISTS.with(|free_lists| {
+                let head = &free_lists[class];
+                for block_index in (live_count..SLAB_BLOCKS).rev() {
+                    let block = base.as_ptr().add(block_index * block_units) as *mut FreeBlock;
+                    let next = head.get();
+                    block.write(FreeBlock { next });
+                    head.set(NonNull::new(block));
+                }
+            });
+        }
+
+        released
+    }
+}
+
+/// Obtains a fresh, uninitialized block of `units` `AllocUnit`s from the
+/// backing allocator — the hosted global allocator (via `Box`) under the
+/// `std` feature, or the embedder-supplied `GlobalAlloc` otherwise.
+#[cfg(feature = "std")]
+fn alloc_block(units: usize) -> *mut AllocUnit {
+    Box::into_raw(Box::<[AllocUnit]>::new_uninit_slice(units)) as *mut MaybeUninit<AllocUnit>
+        as *mut AllocUnit
+}
+
+/// Returns a block obtained from `alloc_block` to the backing allocator.
+///
+/// # Safety
+/// - `pointer`/`units` must match a prior `alloc_block` call exactly.
+#[cfg(feature = "std")]
+unsafe fn dealloc_block(pointer: *mut AllocUnit, units: usize) {
+    unsafe {
+        drop(Box::from_raw(std::slice::from_raw_parts_mut(
+            pointer as *mut MaybeUninit<AllocUnit>,
+            units,
+        )));
+    }
+}
+
+/// Obtains a fresh, uninitialized block of `units` `AllocUnit`s from the
+/// embedder-supplied `GlobalAlloc` (see `platform`).
+#[cfg(not(feature = "std"))]
+fn alloc_block(units: usize) -> *mut AllocUnit {
+    unsafe {
+        platform::alloc_raw(units * size_of::<AllocUnit>(), align_of::<AllocUnit>()) as *mut AllocUnit
+    }
+}
+
+/// Returns a block obtained from `alloc_block` to the embedder-supplied
+/// `GlobalAlloc`.
+///
+/// # Safety
+/// - `pointer`/`units` must match a prior `alloc_block` call exactly.
+#[cfg(not(feature = "std"))]
+unsafe fn dealloc_block(pointer: *mut AllocUnit, units: usize) {
+    unsafe {
+        platform::dealloc_raw(
+            pointer as *mut u8,
+            units * size_of::<AllocUnit>(),
+            align_of::<AllocUnit>(),
+        );
+    }
+}
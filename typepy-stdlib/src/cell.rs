@@ -0,0 +1,28 @@
+use core::cell::UnsafeCell;
+
+/// Single-threaded static cell, replacing `thread_local!` for globals that
+/// only ever run on the one thread of execution a TypePy program has
+/// (hosted or freestanding). `thread_local!` requires `std`, so it can't
+/// survive the `no_std` build; `StaticCell` is `core`-only and gives the
+/// same call-site shape (`CELL.with(|inner| ...)`) so nothing downstream
+/// had to change when this replaced the old `thread_local!` blocks.
+///
+/// # Safety
+/// `StaticCell<T>` is `Sync` unconditionally, which is only sound because
+/// the runtime never accesses these statics from more than one thread.
+pub(crate) struct StaticCell<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for StaticCell<T> {}
+
+impl<T> StaticCell<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        StaticCell(UnsafeCell::new(value))
+    }
+
+    /// Runs `f` with a reference to the cell's contents.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        // Safety: single-threaded by construction (see the `Sync` impl
+        // above), so an exclusive borrow is never live concurrently.
+        f(unsafe { &*self.0.get() })
+    }
+}
@@ -0,0 +1,144 @@
+use super::*;
+use alloc_crate::collections::BTreeSet;
+
+/// Optional sliding compaction phase (Lisp2 three-pass algorithm) layered
+/// on top of the mark-and-sweep collector in `gc.rs`, run periodically
+/// (see `gc::COMPACTION_INTERVAL`) right after a sweep completes.
+///
+/// Sweeping alone only frees dead class-sized blocks onto `alloc.rs`'s
+/// free lists for reuse within their slab — a slab that empties out
+/// entirely is never handed back (`alloc::grow_slab`'s slabs are
+/// otherwise never freed). This pass slides each slab's surviving objects
+/// down to a contiguous prefix using `Object::gc_forward` as the
+/// forwarding pointer, then releases any slab left with no live objects,
+/// reclaiming committed space instead of holding it open forever.
+/// Large objects (outside every size class) already sit in their own
+/// dedicated allocation with no internal fragmentation, so they are left
+/// in place.
+///
+/// # Safety
+/// - Must run only right after a completed sweep, with the gray worklist
+///   empty (every live object already reset to white) — `gc.rs` only
+///   calls this between cycles.
+pub(crate) unsafe fn compact(stack_frame_base: *const u64, stack_pointer: *const u64) {
+    unsafe {
+        // The post-sweep `gc_next` chain is exactly the live set.
+        let live: BTreeSet<usize> = {
+            let mut set = BTreeSet::new();
+            let mut cursor = GC_HEAD.with(|gc_head| gc_head.get());
+            while let Some(object) = cursor {
+                set.insert(object.as_ptr() as usize);
+                cursor = (*object.as_ptr()).gc_next;
+            }
+            set
+        };
+
+        // Pass 1: compute forwarding addresses. Every live object defaults
+        // to identity (unmoved — this covers large objects, which never
+        // move); slab-resident objects are overwritten below with their
+        // slid destination within the same slab.
+        for &address in &live {
+            let object_ptr = address as *mut Object;
+            (*object_ptr).gc_forward = NonNull::new(object_ptr);
+        }
+
+        // For each class/slab, find which of its blocks are live (in
+        // ascending address order, since blocks within a slab sit at fixed
+        // offsets) and assign each a forwarding address at the front of
+        // the slab's surviving prefix.
+        let class_count = alloc::class_count();
+        let mut new_slabs: Vec<Vec<(NonNull<AllocUnit>, usize)>> = Vec::with_capacity(class_count);
+        for class in 0..class_count {
+            let block_units = alloc::class_block_units(class);
+            let slabs = alloc::slab_snapshot(class);
+            let mut rebuilt = Vec::with_capacity(slabs.len());
+            for (base, block_count) in slabs {
+                let mut live_count = 0;
+                for block_index in 0..block_count {
+                    let address = base.as_ptr().add(block_index * block_units) as usize;
+                    if live.contains(&address) {
+                        let dest = base.as_ptr().add(live_count * block_units) as *mut Object;
+                        (*(address as *mut Object)).gc_forward = NonNull::new(dest);
+                        live_count += 1;
+                    }
+                }
+                rebuilt.push((base, live_count));
+            }
+            new_slabs.push(rebuilt);
+        }
+
+        // Pass 2: redirect every reference — roots, live objects' traced
+        // fields, live objects' weak fields, and the `GC_HEAD`/`gc_next`
+        // singly linked list that threads every live object together — to
+        // the forwarded address, before anything moves. Weak fields are
+        // included here (unlike in `gc::scan_children`) because moving an
+        // object must not silently turn its weak references into dangling
+        // pointers; `GC_HEAD`/`gc_next` are included because `core::ptr::copy`
+        // below moves each object's header (including its `gc_next`) along
+        // with it verbatim, so unless that field is redirected first, it
+        // keeps pointing at its successor's pre-move address forever —
+        // corrupting the very list `gc::sweep` and the next `compact` walk
+        // both rely on being exactly the live set.
+        gc::for_each_root_slot(stack_frame_base, stack_pointer, |slot| fixup_slot(slot));
+        GC_HEAD.with(|gc_head| {
+            if let Some(head) = gc_head.get() {
+                gc_head.set((*head.as_ptr()).gc_forward);
+            }
+        });
+        for &address in &live {
+            gc::for_each_child_slot(address as *mut Object, |slot| fixup_slot(slot));
+            gc::for_each_weak_slot(address as *mut Object, |slot| fixup_slot(slot));
+            let object_ptr = address as *mut Object;
+            fixup_slot(core::ptr::addr_of!((*object_ptr).gc_next) as *const u64);
+        }
+
+        // Pass 3: physically slide each slab's live objects down to a
+        // contiguous prefix. Ascending block order means destination
+        // never exceeds source, so a forward `copy` (overlap-safe, unlike
+        // `copy_nonoverlapping`) never clobbers a not-yet-moved object.
+        // Then rebuild the class's free list/slab table, releasing any
+        // slab left with no survivors.
+        let mut released_units = 0;
+        for class in 0..class_count {
+            let block_units = alloc::class_block_units(class);
+            let slabs = alloc::slab_snapshot(class);
+            for (slab_index, &(base, block_count)) in slabs.iter().enumerate() {
+                let live_count = new_slabs[class][slab_index].1;
+                let mut dest_index = 0;
+                for block_index in 0..block_count {
+                    let address = base.as_ptr().add(block_index * block_units) as usize;
+                    if live.contains(&address) {
+                        if dest_index != block_index {
+                            core::ptr::copy(
+                                base.as_ptr().add(block_index * block_units),
+                                base.as_ptr().add(dest_index * block_units),
+                                block_units,
+                            );
+                        }
+                        dest_index += 1;
+                    }
+                }
+                debug_assert_eq!(dest_index, live_count);
+            }
+            released_units += alloc::rebuild_after_compaction(class, &new_slabs[class]);
+        }
+
+        CURRENT_SPACE.with(|current_space| current_space.set(current_space.get() - released_units));
+    }
+}
+
+// Rewrites a reference slot to its pointee's forwarded address, if any. A
+// no-op for null slots; every live object got a `gc_forward` in pass 1, so
+// any non-null slot here is guaranteed to have one.
+unsafe fn fixup_slot(slot: *const u64) {
+    unsafe {
+        let raw = *slot;
+        if raw == 0 {
+            return;
+        }
+        let object_ptr = raw as *mut Object;
+        if let Some(forward) = (*object_ptr).gc_forward {
+            *(slot as *mut u64) = forward.as_ptr() as u64;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Marshalling helpers for calling `extern "C"` functions declared by a
+//! TypePy program (the `extern { fn ... }` surface). This module only
+//! covers the runtime side of the conversion: the frontend/typecheck
+//! accept pass and the codegen call-emission live in the compiler, which
+//! isn't part of this crate.
+//!
+//! As of this writing the compiler side doesn't exist either — there is no
+//! end-to-end way for a TypePy program to declare or call an extern
+//! function yet, and this isn't a gap that can be closed from
+//! `typepy-stdlib` alone: the `chocopy` crate's `frontend`/`typecheck`/
+//! `codegen` modules (`chocopy/src/common`, `chocopy/src/core`) aren't
+//! present in this source tree to add `extern`-declaration support to.
+//! Only `main.rs`'s `--link-lib` flag (which just threads a linker flag
+//! through, and now warns that it's a no-op) and this module's marshalling
+//! helpers exist so far.
+//!
+//! `Int`/`Bool` TypePy values are already C-ABI-compatible (see
+//! `object::Type`) and need no conversion; `Str` needs a NUL terminator it
+//! doesn't otherwise carry (its bytes live at `size_of::<ArrayObject>()`
+//! with an explicit `len`, not a terminator), handled by [`to_cstr`] and
+//! [`from_cstr`] below.
+//!
+//! # Safety (GC rooting)
+//! A TypePy object handed to C as a raw pointer must stay reachable for
+//! the duration of the call: codegen must keep the marshalled argument (or
+//! the `Str` it was derived from) live in a stack slot covered by the
+//! reference bitmap until the call returns, exactly as it would for any
+//! other live reference across a safepoint.
+
+use super::*;
+
+/// Produces a NUL-terminated copy of `pointer`'s bytes (`pointer` must be
+/// a `Str`), suitable for passing to a C function expecting `char*`. The
+/// copy is independent of the GC heap — free it with [`free_cstr`] once
+/// the C call returns.
+///
+/// # Safety
+/// - `pointer` must be a valid, non-null `Str` object.
+#[unsafe(export_name = "$to_cstr")]
+pub unsafe extern "C" fn to_cstr(pointer: *mut Object) -> *mut u8 {
+    unsafe {
+        if pointer.is_null() || !matches!((*(*pointer).prototype).type_tag, Type::Str) {
+            invalid_arg();
+        }
+        let object = pointer as *mut ArrayObject;
+        let len = (*object).len as usize;
+        let source = object.offset(1) as *const u8;
+
+        let buffer = alloc_c_bytes(len + 1);
+        core::ptr::copy_nonoverlapping(source, buffer, len);
+        buffer.add(len).write(0);
+        buffer
+    }
+}
+
+/// Frees a buffer previously returned by [`to_cstr`].
+///
+/// # Safety
+/// - `pointer` must have been returned by `to_cstr` with the same `len`
+///   (the `Str`'s length, not counting the terminator) it was produced
+///   with.
+#[unsafe(export_name = "$free_cstr")]
+pub unsafe extern "C" fn free_cstr(pointer: *mut u8, len: i32) {
+    unsafe {
+        dealloc_c_bytes(pointer, len as usize + 1);
+    }
+}
+
+/// Allocates a new `Str` object by copying bytes from a NUL-terminated C
+/// string (e.g. one returned by an `extern` function), stopping at the
+/// first NUL byte.
+///
+/// # Safety
+/// - `init` must have been called.
+/// - `pointer` must be non-null and point to a valid NUL-terminated
+///   string.
+/// - `rbp`/`rsp` must describe a valid stack frame.
+#[unsafe(export_name = "$from_cstr")]
+pub unsafe extern "C" fn from_cstr(
+    pointer: *const u8,
+    rbp: *const u64,
+    rsp: *const u64,
+) -> *mut Object {
+    unsafe {
+        let mut len = 0usize;
+        while *pointer.add(len) != 0 {
+            len += 1;
+        }
+
+        let str_proto = INIT_PARAM.with(|init_param| (*init_param.get()).str_prototype);
+        let object = alloc_obj(str_proto, len as u64, rbp, rsp);
+        core::ptr::copy_nonoverlapping(
+            pointer,
+            (object as *mut u8).add(size_of::<ArrayObject>()),
+            len,
+        );
+        object
+    }
+}
+
+#[cfg(feature = "std")]
+fn alloc_c_bytes(len: usize) -> *mut u8 {
+    Box::into_raw(Box::<[u8]>::new_uninit_slice(len)) as *mut MaybeUninit<u8> as *mut u8
+}
+
+#[cfg(feature = "std")]
+unsafe fn dealloc_c_bytes(pointer: *mut u8, len: usize) {
+    unsafe {
+        drop(Box::from_raw(std::slice::from_raw_parts_mut(
+            pointer as *mut MaybeUninit<u8>,
+            len,
+        )));
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn alloc_c_bytes(len: usize) -> *mut u8 {
+    unsafe { platform::alloc_raw(len, align_of::<u8>()) }
+}
+
+#[cfg(not(feature = "std"))]
+unsafe fn dealloc_c_bytes(pointer: *mut u8, len: usize) {
+    unsafe {
+        platform::dealloc_raw(pointer, len, align_of::<u8>());
+    }
+}
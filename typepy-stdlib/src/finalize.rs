@@ -0,0 +1,68 @@
+use super::*;
+use alloc_crate::collections::BTreeMap;
+
+/// A class's finalizer: called once with the object about to be reclaimed,
+/// after it has been resurrected for its last cycle (see `gc::sweep`), and
+/// before its memory is actually freed.
+pub(crate) type Finalizer = unsafe extern "C" fn(*mut Object);
+
+// Maps a class's `Prototype` (by address) to its finalizer. Keyed on the
+// prototype rather than stored as a `Prototype` field: unlike
+// `reference_bitmap`/`weak_bitmap`, a finalizer's natural home ("right after
+// the method pointers", per the object model's existing convention for
+// per-class function pointers) sits at an offset that varies with the
+// class's method count, which this crate has no fixed field for — so
+// `$register_finalizer` records it here instead, once per class at module
+// init.
+static FINALIZERS: StaticCell<RefCell<BTreeMap<usize, Finalizer>>> =
+    StaticCell::new(RefCell::new(BTreeMap::new()));
+
+// Finalizers enqueued by the most recent `gc::sweep`, run once sweeping
+// (and thus iteration over `GC_HEAD`) has finished.
+static PENDING: StaticCell<RefCell<Vec<(NonNull<Object>, Finalizer)>>> =
+    StaticCell::new(RefCell::new(Vec::new()));
+
+/// Registers `finalizer` as the finalizer for every instance of `prototype`.
+/// Codegen calls this once per class declaring a finalizer, at module init
+/// (alongside `$init`), before any instance of that class is allocated.
+///
+/// # Safety
+/// - `prototype` must be a valid, permanently-live `Prototype` pointer (the
+///   compiled program's static class metadata).
+#[unsafe(export_name = "$register_finalizer")]
+pub unsafe extern "C" fn register_finalizer(prototype: *const Prototype, finalizer: Finalizer) {
+    FINALIZERS.with(|finalizers| {
+        finalizers.borrow_mut().insert(prototype as usize, finalizer);
+    });
+}
+
+/// Looks up `prototype`'s finalizer, if `$register_finalizer` was ever
+/// called for it. `alloc_obj` consults this to set `OBJECT_FLAG_FINALIZABLE`
+/// on new instances; `gc::sweep` consults it again to know what to enqueue.
+pub(crate) fn finalizer_for(prototype: *const Prototype) -> Option<Finalizer> {
+    FINALIZERS.with(|finalizers| finalizers.borrow().get(&(prototype as usize)).copied())
+}
+
+/// Queues `finalizer` to run against `object` once the in-progress sweep
+/// finishes walking `GC_HEAD` — running it mid-walk would let arbitrary
+/// compiled code reallocate (and so mutate `GC_HEAD`) while `gc::sweep` is
+/// still iterating it.
+pub(crate) fn enqueue(object: *mut Object, finalizer: Finalizer) {
+    PENDING.with(|pending| {
+        pending
+            .borrow_mut()
+            .push((NonNull::new(object).expect("sweep never enqueues a null object"), finalizer))
+    });
+}
+
+/// Runs every finalizer queued by the sweep that just completed.
+///
+/// # Safety
+/// - Must run only after `gc::sweep` has finished walking `GC_HEAD` for the
+///   cycle that queued these finalizers.
+pub(crate) unsafe fn run_pending() {
+    let pending = PENDING.with(|pending| core::mem::take(&mut *pending.borrow_mut()));
+    for (object, finalizer) in pending {
+        unsafe { finalizer(object.as_ptr()) };
+    }
+}
@@ -0,0 +1,453 @@
+use super::*;
+use alloc_crate::collections::BTreeSet;
+
+/// Tri-color mark state, packed into `Object::gc_count` (unused by this
+/// collection mode — only the reference-counting mode in `refcount.rs`
+/// needs the full 64-bit range). White objects survived no marking yet
+/// this cycle (or were swept last cycle); gray objects are reached but
+/// have unscanned children; black objects are fully scanned. The mark
+/// phase maintains the invariant "no black object references a white
+/// object" via [`write_barrier`].
+const WHITE: u64 = 0;
+const GRAY: u64 = 1;
+const BLACK: u64 = 2;
+
+/// Number of gray objects scanned per `perform_incremental_gc` call. Bounds
+/// how much marking work is interleaved with a single allocation, so a
+/// cycle's pause is a slice rather than the whole heap.
+const MARK_BUDGET: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Marking,
+}
+
+// Current point in the incremental cycle. `Idle` between cycles; `Marking`
+// while the gray worklist is being drained.
+static PHASE: StaticCell<Cell<Phase>> = StaticCell::new(Cell::new(Phase::Idle));
+// Objects reached but not yet scanned for outgoing references.
+static GRAY_WORKLIST: StaticCell<RefCell<Vec<NonNull<Object>>>> =
+    StaticCell::new(RefCell::new(Vec::new()));
+
+/// Number of completed sweep cycles between mark-compact passes (see
+/// `compact::compact`). Sweeping alone never returns an emptied slab to
+/// the global allocator, so this runs periodically rather than every
+/// cycle, since sliding a slab's survivors costs a full heap walk.
+const COMPACTION_INTERVAL: u32 = 8;
+static CYCLES_SINCE_COMPACTION: StaticCell<Cell<u32>> = StaticCell::new(Cell::new(0));
+
+fn color(object_ptr: *mut Object) -> u64 {
+    unsafe { (*object_ptr).gc_count }
+}
+
+fn set_color(object_ptr: *mut Object, color: u64) {
+    unsafe {
+        (*object_ptr).gc_count = color;
+    }
+}
+
+/// Grays `object_ptr` (pushing it onto the worklist for later scanning) if
+/// it is currently white. A no-op for gray/black objects and null
+/// pointers, so callers can shade a possibly-null reference unconditionally.
+fn shade_gray(object_ptr: *mut Object) {
+    if object_ptr.is_null() || color(object_ptr) != WHITE {
+        return;
+    }
+    set_color(object_ptr, GRAY);
+    GRAY_WORKLIST.with(|worklist| {
+        worklist
+            .borrow_mut()
+            .push(NonNull::new(object_ptr).expect("checked non-null above"))
+    });
+}
+
+// Reads a little-endian i32 from a pointer to memory.
+unsafe fn read_i32_le(ptr: *const u8) -> i32 {
+    unsafe {
+        let mut buffer = [0; 4];
+        // Copy 4 bytes from the pointer into buffer.
+        core::ptr::copy_nonoverlapping(ptr, buffer.as_mut_ptr(), 4);
+        // Convert bytes to i32 assuming little-endian encoding.
+        i32::from_le_bytes(buffer)
+    }
+}
+
+// Gets the reference bitmap for the current stack frame's return address (RIP).
+// This map indicates which stack slots contain object references.
+unsafe fn get_reference_bitmap_from_rip(rip: *const u8) -> *const u8 {
+    unsafe {
+        // Read the 4-byte offset at RIP + 3.
+        let offset = read_i32_le(rip.offset(3));
+        // Return the address of the bitmap by offsetting RIP.
+        rip.offset((offset + 7) as isize)
+    }
+}
+
+/// Visits every root slot — stack slots (walked via `bottom_frame`) and
+/// globals (via `global_map`) — passing each to `visit`. Shared by the
+/// tri-color marker (grays roots) and the mark-compact fix-up pass
+/// (rewrites roots to forwarded addresses).
+pub(crate) unsafe fn for_each_root_slot(
+    stack_frame_base: *const u64,
+    stack_pointer: *const u64,
+    mut visit: impl FnMut(*const u64),
+) {
+    unsafe {
+        let init_param = INIT_PARAM.with(|param| &*param.get());
+
+        let mut return_address = *stack_pointer.offset(-1) as *const u8;
+        let mut current_frame = stack_frame_base;
+        loop {
+            // Get reference bitmap from the function's return address.
+            let ref_map = get_reference_bitmap_from_rip(return_address);
+            // Read min and max indices of the map.
+            let min_index = read_i32_le(ref_map);
+            let max_index = read_i32_le(ref_map.offset(4));
+
+            for index in min_index..=max_index {
+                let map_index = (index - min_index) as usize;
+                // Determine if the stack slot at this index is a reference.
+                let flag = *ref_map.add(8 + map_index / 8) & (1 << (map_index % 8));
+                if flag != 0 {
+                    visit(current_frame.offset(index as isize));
+                }
+            }
+
+            if current_frame == init_param.bottom_frame {
+                // Reached bottom of stack, done with stack roots.
+                break;
+            }
+            // Unwind to previous frame (linked list of stack frames).
+            return_address = *current_frame.offset(1) as *const u8;
+            current_frame = *current_frame as *const u64;
+        }
+
+        // Visit global variables.
+        for index in 0..init_param.global_size / 8 {
+            let idx = index as usize;
+            // Determine if the global slot contains a reference.
+            let flag = *init_param.global_map.add(idx / 8) & (1 << (idx % 8));
+            if flag != 0 {
+                visit(init_param.global_section.add(idx));
+            }
+        }
+    }
+}
+
+/// Visits every reference field of `object_ptr`, using the
+/// `reference_bitmap`/`ObjList` traversal common to every collector in
+/// this crate (the tri-color marker, the mark-compact fix-up pass, and
+/// `refcount::for_each_child`).
+///
+/// Relies on the invariant documented on `alloc_obj`: every reference slot
+/// reachable from here has been written by codegen (or zeroed by the
+/// allocator, for array payloads) before the next allocation safepoint, so
+/// it is never uninitialized at this point.
+pub(crate) unsafe fn for_each_child_slot(object_ptr: *mut Object, mut visit: impl FnMut(*const u64)) {
+    unsafe {
+        let handle = handle::ObjHandle::new(object_ptr).expect("object_ptr checked non-null by callers");
+        match (*handle.prototype()).type_tag {
+            Type::Other => {
+                // This is a regular object with fields, some of which may be references.
+                let field_count = ((*handle.prototype()).size / 8) as usize;
+                let ref_bitmap = (*handle.prototype()).reference_bitmap;
+                for i in 0..field_count {
+                    // Check if the i-th field is a reference by looking up the bitmap.
+                    let flag = *ref_bitmap.add(i / 8) & (1 << (i % 8));
+                    if flag != 0 {
+                        visit(handle.attribute_ptr(i));
+                    }
+                }
+            }
+            Type::ObjList => {
+                // This is an array of references (e.g. list of objects).
+                let array = handle::ArrayHandle::new(object_ptr).expect("ObjList is always array-shaped");
+                for i in 0..array.array_len() {
+                    // Follow each element of the array.
+                    visit(array.array_element_ptr(i as usize, 8) as *const u64);
+                }
+            }
+            _ => (), // Other types do not contain references.
+        }
+    }
+}
+
+/// Visits every weak-reference field of `object_ptr` (per its prototype's
+/// `weak_bitmap`), the counterpart to `for_each_child_slot` for slots the
+/// collector must locate without tracing through them: a weak field never
+/// keeps its referent alive, so it is never set in `reference_bitmap`, only
+/// in this separate bitmap. Shared by `gc::sweep` (nulls a slot once its
+/// referent dies) and `compact::compact` (redirects a slot once its
+/// referent moves). A no-op for object kinds with no weak fields
+/// (`weak_bitmap` null) — arrays never carry weak elements.
+pub(crate) unsafe fn for_each_weak_slot(object_ptr: *mut Object, mut visit: impl FnMut(*const u64)) {
+    unsafe {
+        let handle = handle::ObjHandle::new(object_ptr).expect("object_ptr checked non-null by callers");
+        let prototype = handle.prototype();
+        let weak_bitmap = (*prototype).weak_bitmap;
+        if weak_bitmap.is_null() {
+            return;
+        }
+        let field_count = ((*prototype).size / 8) as usize;
+        for i in 0..field_count {
+            let flag = *weak_bitmap.add(i / 8) & (1 << (i % 8));
+            if flag != 0 {
+                visit(handle.attribute_ptr(i));
+            }
+        }
+    }
+}
+
+// Grays the object referenced by a root slot (a stack slot or global),
+// unless it is null.
+unsafe fn gray_root(var_ptr: *const u64) {
+    unsafe {
+        if *var_ptr != 0 {
+            shade_gray(*var_ptr as *mut Object);
+        }
+    }
+}
+
+/// Grays every root. Called at the start of every `perform_incremental_gc`
+/// slice, before its `mark_step`.
+unsafe fn gray_roots(stack_frame_base: *const u64, stack_pointer: *const u64) {
+    unsafe { for_each_root_slot(stack_frame_base, stack_pointer, |slot| gray_root(slot)) }
+}
+
+// Grays every reference field reachable from a gray object.
+unsafe fn scan_children(object_ptr: *mut Object) {
+    unsafe { for_each_child_slot(object_ptr, |slot| gray_root(slot)) }
+}
+
+// Pops up to `budget` gray objects, scanning each one's children (graying
+// any white ones) and blackening it. This is the unit of work interleaved
+// with the mutator: one call does a bounded slice, not the whole heap.
+unsafe fn mark_step(mut budget: usize) {
+    unsafe {
+        while budget > 0 {
+            let next = GRAY_WORKLIST.with(|worklist| worklist.borrow_mut().pop());
+            let Some(object) = next else {
+                break;
+            };
+            let object_ptr = object.as_ptr();
+            scan_children(object_ptr);
+            set_color(object_ptr, BLACK);
+            budget -= 1;
+        }
+    }
+}
+
+// Transitively protects everything reachable from `object_ptr` (itself
+// included) from reclamation this sweep, by adding it to `protected`. Used
+// to extend a resurrected finalizable object's one-more-cycle reprieve to
+// its whole subgraph: a non-finalizable child reachable only through it is
+// white too and, without this, would be freed in this very sweep — before
+// the finalizer enqueued for its owner ever runs and dereferences it.
+// `protected.insert` returning `false` (already visited) ends the
+// recursion, so a cycle through already-protected objects terminates.
+unsafe fn protect_reachable(object_ptr: *mut Object, protected: &mut BTreeSet<usize>) {
+    unsafe {
+        if !protected.insert(object_ptr as usize) {
+            return;
+        }
+        for_each_child_slot(object_ptr, |slot| {
+            let child = *slot as *mut Object;
+            if !child.is_null() {
+                protect_reachable(child, protected);
+            }
+        });
+    }
+}
+
+// Sweeps the heap once the gray worklist has fully drained: every
+// surviving object is black (reset to white for the next cycle) and every
+// object still white was never reached this cycle, so it is either
+// reclaimed or, if finalizable and not already pending, resurrected for one
+// more cycle to run its finalizer — along with everything reachable from
+// it, so the finalizer never dereferences already-freed memory.
+unsafe fn sweep() {
+    unsafe {
+        // Every object kept alive this cycle purely so a resurrected
+        // finalizable object's finalizer can safely read it: the
+        // finalizable roots themselves plus their full reachable subgraph.
+        let mut protected: BTreeSet<usize> = BTreeSet::new();
+        let mut cursor = GC_HEAD.with(|gc_head| gc_head.get());
+        while let Some(object) = cursor {
+            let object_ptr = object.as_ptr();
+            let flags = (*object_ptr).gc_flags;
+            let resurrecting = color(object_ptr) != BLACK
+                && flags & OBJECT_FLAG_FINALIZABLE != 0
+                && flags & OBJECT_FLAG_FINALIZATION_PENDING == 0;
+            if resurrecting {
+                protect_reachable(object_ptr, &mut protected);
+            }
+            cursor = (*object_ptr).gc_next;
+        }
+
+        // Every white object that will actually be reclaimed this cycle
+        // (finalizable-pending roots and anything `protected` above are
+        // white too, but are kept around for one more cycle, so they
+        // aren't "dying" yet). Collected up front, before anything is
+        // freed or resurrected, so the weak slot walk below sees a stable
+        // view of the cycle's outcome.
+        let mut dying: BTreeSet<usize> = BTreeSet::new();
+        let mut cursor = GC_HEAD.with(|gc_head| gc_head.get());
+        while let Some(object) = cursor {
+            let object_ptr = object.as_ptr();
+            if color(object_ptr) != BLACK && !protected.contains(&(object_ptr as usize)) {
+                dying.insert(object_ptr as usize);
+            }
+            cursor = (*object_ptr).gc_next;
+        }
+
+        // Null every live weak slot pointing at an object about to be
+        // reclaimed, so readers observe None/0 instead of a dangling
+        // pointer once it's actually freed below.
+        if !dying.is_empty() {
+            let mut cursor = GC_HEAD.with(|gc_head| gc_head.get());
+            while let Some(object) = cursor {
+                let object_ptr = object.as_ptr();
+                for_each_weak_slot(object_ptr, |slot| {
+                    if dying.contains(&(*slot as usize)) {
+                        *(slot as *mut u64) = 0;
+                    }
+                });
+                cursor = (*object_ptr).gc_next;
+            }
+        }
+
+        let mut head = GC_HEAD.with(|gc_head| gc_head.get());
+        let mut cursor = &mut head;
+        let mut released_units = 0;
+
+        while let Some(object) = *cursor {
+            let object_ptr = object.as_ptr();
+            if color(object_ptr) == BLACK {
+                // Keep this object; reset to white for the next cycle.
+                set_color(object_ptr, WHITE);
+                cursor = &mut (*object_ptr).gc_next;
+                continue;
+            }
+
+            let flags = (*object_ptr).gc_flags;
+            if flags & OBJECT_FLAG_FINALIZABLE != 0 && flags & OBJECT_FLAG_FINALIZATION_PENDING == 0 {
+                // Resurrect for one more cycle: mark it so next time it goes
+                // unreached it's reclaimed outright, reset it to white (it
+                // isn't being traced, but every live object's color must be
+                // white going into the next cycle), and queue its finalizer
+                // to run once this sweep finishes walking the list.
+                (*object_ptr).gc_flags = flags | OBJECT_FLAG_FINALIZATION_PENDING;
+                set_color(object_ptr, WHITE);
+                if let Some(finalizer) = finalize::finalizer_for((*object_ptr).prototype) {
+                    finalize::enqueue(object_ptr, finalizer);
+                }
+                cursor = &mut (*object_ptr).gc_next;
+                continue;
+            }
+
+            if protected.contains(&(object_ptr as usize)) {
+                // Reachable only through a resurrected finalizable object
+                // above; kept alive for the same one cycle as its owner,
+                // without enqueuing a finalizer of its own.
+                set_color(object_ptr, WHITE);
+                cursor = &mut (*object_ptr).gc_next;
+                continue;
+            }
+
+            // Unreached this cycle (and not being resurrected or
+            // transitively protected); remove from GC list.
+            *cursor = (*object_ptr).gc_next;
+
+            // Compute size of object in allocation units.
+            let size_units = calculate_size((*object_ptr).prototype, || {
+                (*(object_ptr as *mut ArrayObject)).len
+            });
+
+            // Hand the block back to the slab allocator. Class-sized
+            // blocks are spliced onto their free list for reuse (no
+            // bytes released); only large objects actually shrink
+            // committed space.
+            released_units += alloc::free_units(object_ptr as *mut AllocUnit, size_units);
+        }
+
+        // Update GC state after sweeping.
+        GC_HEAD.with(|gc_head| gc_head.set(head));
+        CURRENT_SPACE.with(|current_space| current_space.set(current_space.get() - released_units));
+
+        // Run any finalizers queued above, now that GC_HEAD is stable.
+        finalize::run_pending();
+    }
+}
+
+/// True while a cycle is in progress (roots grayed, worklist not yet
+/// drained). `alloc_obj` consults this to keep feeding an in-progress
+/// cycle incremental work even below `THRESHOLD_SPACE`.
+pub(crate) fn in_progress() -> bool {
+    PHASE.with(|phase| phase.get()) == Phase::Marking
+}
+
+/// Advances the incremental collector by one bounded slice of work: starts
+/// a new cycle if idle, grays every root, scans up to `MARK_BUDGET` gray
+/// objects, and sweeps once the worklist drains. Returns whether a cycle
+/// completed (swept) during this call, so the caller can recompute
+/// `THRESHOLD_SPACE` from the post-sweep heap size.
+pub unsafe fn perform_incremental_gc(stack_frame_base: *const u64, stack_pointer: *const u64) -> bool {
+    unsafe {
+        PHASE.with(|phase| phase.set(Phase::Marking));
+
+        // Re-gray every root on every slice, not just at cycle start.
+        // `write_barrier` only fires for stores into an existing heap
+        // object's field (the "black container, white target" case) — a
+        // plain stack/local assignment never goes through it, so an object
+        // allocated mid-cycle and held only in a fresh stack slot has no
+        // other path to being grayed. `shade_gray` is a no-op past the
+        // first call for anything already gray/black, so repeating this
+        // every slice only costs a walk, not correctness or duplicate work;
+        // it also doubles as the final synchronous root re-scan right
+        // before the worklist-drain-triggered `sweep()` below.
+        gray_roots(stack_frame_base, stack_pointer);
+
+        mark_step(MARK_BUDGET);
+
+        if GRAY_WORKLIST.with(|worklist| worklist.borrow().is_empty()) {
+            sweep();
+            PHASE.with(|phase| phase.set(Phase::Idle));
+
+            let cycles = CYCLES_SINCE_COMPACTION.with(|cycles| {
+                let next = cycles.get() + 1;
+                cycles.set(next);
+                next
+            });
+            if cycles >= COMPACTION_INTERVAL {
+                CYCLES_SINCE_COMPACTION.with(|cycles| cycles.set(0));
+                compact::compact(stack_frame_base, stack_pointer);
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Write barrier for storing a reference into a GC-tracked field. Codegen
+/// emits this (keyed off the `reference_bitmap`) in place of a bare
+/// pointer write at every reference-field assignment, so the collector
+/// never misses a store made mid-cycle: if `container` is already black
+/// and the newly stored `target` is white, `target` is shaded gray,
+/// preserving "no black object references a white object" without having
+/// to re-gray (and rescan) `container` itself.
+///
+/// # Safety
+/// - `slot` must be a valid, properly aligned pointer to the field being
+///   written.
+#[unsafe(export_name = "$write_barrier")]
+pub unsafe extern "C" fn write_barrier(container: *mut Object, slot: *mut u64, target: *mut Object) {
+    unsafe {
+        *slot = target as u64;
+        if in_progress() && !container.is_null() && color(container) == BLACK {
+            shade_gray(target);
+        }
+    }
+}
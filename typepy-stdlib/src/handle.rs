@@ -0,0 +1,99 @@
+//! A safe, typed layer over the raw `*const Object`/`*const Prototype`
+//! pointer arithmetic and the hand-computed offsets in `object.rs`
+//! (`OBJECT_ATTRIBUTE_OFFSET`, `ARRAY_ELEMENT_OFFSET`, ...). Every method
+//! here computes its offset from those same constants, so this changes
+//! nothing about the underlying `#[repr(C)]` layout — it just centralizes
+//! the unsafe arithmetic and documents each access's validity requirement
+//! in one place, instead of each of `gc.rs`/`refcount.rs`/`ffi.rs`/`lib.rs`
+//! repeating it inline.
+//!
+//! `ObjHandle`/`ArrayHandle` guarantee non-null via `NonNull`; constructing
+//! one from a possibly-null raw pointer returns `Option`, matching the
+//! null-check every existing call site already performs by hand.
+//!
+//! Adopted so far by `gc::for_each_child_slot`/`gc::for_each_weak_slot` and
+//! `refcount::for_each_child` — the three sites that walked this exact
+//! attribute/element arithmetic by hand. The rest of this crate's raw
+//! pointer arithmetic (`ffi.rs`'s C string marshalling, `alloc.rs`'s slab
+//! bookkeeping) isn't `Object`/`ArrayObject` field access and doesn't fit
+//! this layer.
+
+use super::*;
+
+/// A non-null handle to a heap object.
+#[derive(Clone, Copy)]
+pub(crate) struct ObjHandle(NonNull<Object>);
+
+impl ObjHandle {
+    /// Wraps a possibly-null raw pointer, returning `None` for null.
+    pub(crate) fn new(pointer: *mut Object) -> Option<Self> {
+        NonNull::new(pointer).map(ObjHandle)
+    }
+
+    #[allow(dead_code)] // Exposed alongside `new`/`attribute_ptr` for symmetry; no caller needs the raw pointer back yet.
+    pub(crate) fn as_ptr(self) -> *mut Object {
+        self.0.as_ptr()
+    }
+
+    /// The object's prototype (method table + layout metadata).
+    ///
+    /// # Safety
+    /// - The pointee must be a live object allocated by `$alloc_obj`.
+    pub(crate) unsafe fn prototype(self) -> *const Prototype {
+        unsafe { (*self.0.as_ptr()).prototype }
+    }
+
+    /// A pointer to the `index`-th 8-byte attribute slot following the
+    /// object header, i.e. `OBJECT_ATTRIBUTE_OFFSET + index * 8`.
+    ///
+    /// # Safety
+    /// - The pointee must be a live, fixed-size (non-array) object.
+    /// - `index` must be within the object's field count
+    ///   (`prototype().size / 8`).
+    pub(crate) unsafe fn attribute_ptr(self, index: usize) -> *mut u64 {
+        unsafe { (self.0.as_ptr() as *mut u8).add(OBJECT_ATTRIBUTE_OFFSET as usize + index * 8) as *mut u64 }
+    }
+}
+
+/// A non-null handle to an array-like heap object (`Str`, `ValueList`,
+/// `ObjList`).
+#[derive(Clone, Copy)]
+pub(crate) struct ArrayHandle(NonNull<ArrayObject>);
+
+impl ArrayHandle {
+    /// Wraps a possibly-null raw pointer, returning `None` for null.
+    pub(crate) fn new(pointer: *mut Object) -> Option<Self> {
+        NonNull::new(pointer as *mut ArrayObject).map(ArrayHandle)
+    }
+
+    #[allow(dead_code)] // Exposed alongside `new`/`array_element_ptr` for symmetry; no caller needs the raw pointer back yet.
+    pub(crate) fn as_ptr(self) -> *mut ArrayObject {
+        self.0.as_ptr()
+    }
+
+    #[allow(dead_code)] // Exposed for call sites that need to cross back into `ObjHandle`'s API; none do yet.
+    pub(crate) fn as_object(self) -> ObjHandle {
+        ObjHandle(self.0.cast())
+    }
+
+    /// The number of elements (`ARRAY_LEN_OFFSET`).
+    ///
+    /// # Safety
+    /// - The pointee must be a live array object.
+    pub(crate) unsafe fn array_len(self) -> u64 {
+        unsafe { (*self.0.as_ptr()).len }
+    }
+
+    /// A pointer to the `index`-th element, `stride` bytes wide (elements
+    /// are 1/4/8 bytes depending on the prototype's `Str`/`ValueList`/
+    /// `ObjList` tag, so the header carries no single element width),
+    /// i.e. `ARRAY_ELEMENT_OFFSET + index * stride`.
+    ///
+    /// # Safety
+    /// - The pointee must be a live array object.
+    /// - `index` must be within `array_len()` and `stride` must match the
+    ///   element width implied by the object's prototype.
+    pub(crate) unsafe fn array_element_ptr(self, index: usize, stride: usize) -> *mut u8 {
+        unsafe { (self.0.as_ptr() as *mut u8).add(ARRAY_ELEMENT_OFFSET as usize + index * stride) }
+    }
+}
@@ -1,29 +1,54 @@
+//! By default this crate is a hosted (`std`) runtime. Building with
+//! `--no-default-features` switches it to a `no_std` core for freestanding
+//! and embedded targets: globals move to a single-threaded static cell
+//! (see `cell`), `$print`/`$input`/the trap exports route through an
+//! embedder-supplied [`platform::Platform`], and allocation goes through
+//! the embedder's `GlobalAlloc` rather than assuming one is registered.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc as alloc_crate;
+
 mod object;
 
+mod cell;
+#[cfg(not(feature = "std"))]
+mod platform;
+
+use alloc_crate::boxed::Box;
+use alloc_crate::vec::Vec;
+use cell::StaticCell;
+use core::cell::Cell;
+use core::cell::RefCell;
+use core::mem::*;
+use core::ptr::*;
 use object::*;
-use std::cell::*;
-use std::mem::*;
+#[cfg(feature = "std")]
 use std::process::{abort, exit};
-use std::ptr::*;
 
+mod alloc;
+mod compact;
+mod ffi;
+mod finalize;
 mod gc;
+mod handle;
+mod refcount;
 
 /// Allocation unit used to measure memory usage in the mark-and-sweep GC.
 #[repr(transparent)]
 #[derive(Clone, Copy)]
 struct AllocUnit(u64);
 
-// Thread-local global variables to hold runtime and GC metadata.
-thread_local! {
-    // Points to initialization parameters passed to runtime.
-    static INIT_PARAM: Cell<*const InitParam> = const { Cell::new(std::ptr::null()) };
-    // Head of the linked list of all allocated GC-tracked objects.
-    static GC_HEAD: Cell<Option<NonNull<Object>>> = const { Cell::new(None) };
-    // Total space currently allocated (in AllocUnits).
-    static CURRENT_SPACE: Cell<usize> = const { Cell::new(0) };
-    // Threshold at which the GC should trigger a collection.
-    static THRESHOLD_SPACE: Cell<usize> = const { Cell::new(1024) };
-}
+// Points to initialization parameters passed to runtime.
+static INIT_PARAM: StaticCell<Cell<*const InitParam>> = StaticCell::new(Cell::new(core::ptr::null()));
+// Head of the linked list of all allocated GC-tracked objects.
+static GC_HEAD: StaticCell<Cell<Option<NonNull<Object>>>> = StaticCell::new(Cell::new(None));
+// Total slab space currently committed from the global allocator (in
+// AllocUnits). Reusing a freed class-sized block doesn't move this;
+// only `alloc::grow_slab` (new slabs), large-object alloc/free, and
+// `compact::compact` (releasing emptied slabs) do.
+static CURRENT_SPACE: StaticCell<Cell<usize>> = StaticCell::new(Cell::new(0));
+// Threshold at which the GC should trigger a collection.
+static THRESHOLD_SPACE: StaticCell<Cell<usize>> = StaticCell::new(Cell::new(1024));
 
 /// Helper to round up memory allocation to nearest unit.
 fn divide_up(value: usize) -> usize {
@@ -58,10 +83,20 @@ pub(crate) unsafe fn calculate_size<F: FnOnce() -> u64>(
 /// Allocates a new TypePy object and tracks it for garbage collection.
 /// Triggers GC if allocation exceeds current threshold.
 ///
+/// The backing store is obtained uninitialized (not zero-filled): only the
+/// `Object`/`ArrayObject` header is written here, plus the element payload
+/// for arrays (zeroed so array GC roots can never read garbage). Fixed-size
+/// object bodies are left uninitialized under the invariant that codegen
+/// writes every field before the next `$alloc_obj` call, the only GC
+/// safepoint — `gc::scan_children` and the sweep never observe a
+/// reference slot between allocation and that point.
+///
 /// # Safety
 /// - Called only after runtime is initialized.
 /// - `prototype` must be valid.
 /// - If allocating an array, `len` must be meaningful.
+/// - The caller (codegen) must initialize every non-array field before the
+///   next call into this function.
 #[unsafe(export_name = "$alloc_obj")]
 pub unsafe extern "C" fn alloc_obj(
     prototype: *const Prototype,
@@ -70,43 +105,80 @@ pub unsafe extern "C" fn alloc_obj(
     rsp: *const u64,
 ) -> *mut Object {
     unsafe {
-        // Check if we need to run GC before allocating
-        if CURRENT_SPACE.with(|current_space| current_space.get())
-            >= THRESHOLD_SPACE.with(|threshold_space| threshold_space.get())
+        // Feed the incremental collector a bounded slice of work: either to
+        // start a new cycle (heap has grown past the threshold) or to keep
+        // draining one already in progress. `THRESHOLD_SPACE` is only
+        // recomputed once a cycle actually completes (sweeps), since a
+        // slice in the middle of marking doesn't reflect the post-sweep
+        // heap size.
+        //
+        // Skipped entirely under the `refcount` feature: that mode reuses
+        // `Object::gc_count` as a live reference count (see `refcount.rs`),
+        // not a tri-color mark state, so letting this run would eventually
+        // cross `THRESHOLD_SPACE` and have `gc::sweep` free almost every
+        // live object whose count isn't coincidentally `2` (`BLACK`).
+        #[cfg(not(feature = "refcount"))]
+        if gc::in_progress()
+            || CURRENT_SPACE.with(|current_space| current_space.get())
+                >= THRESHOLD_SPACE.with(|threshold_space| threshold_space.get())
         {
-            gc::perform_mark_and_sweep_gc(rbp, rsp);
-            let current = CURRENT_SPACE.with(|current_space| current_space.get());
-            let threshold = std::cmp::max(1024, current * 2);
-            THRESHOLD_SPACE.with(|threshold_space| threshold_space.set(threshold));
+            if gc::perform_incremental_gc(rbp, rsp) {
+                let current = CURRENT_SPACE.with(|current_space| current_space.get());
+                let threshold = core::cmp::max(1024, current * 2);
+                THRESHOLD_SPACE.with(|threshold_space| threshold_space.set(threshold));
+            }
         }
 
         // Calculate size in allocation units
         let size = calculate_size(prototype, || len);
 
-        // Allocate raw memory for the object
-        let pointer = Box::into_raw(vec![AllocUnit(0); size].into_boxed_slice())
-            as *mut AllocUnit as *mut Object;
+        // Allocate raw, uninitialized memory for the object (no memset),
+        // popping from the matching size-class free list where possible.
+        let (pointer, committed) = alloc::alloc_units(size);
+        let pointer = pointer as *mut Object;
 
-        // Update GC memory tracking
-        CURRENT_SPACE.with(|current_space| current_space.set(current_space.get() + size));
+        // Update GC memory tracking with newly committed slab bytes, not
+        // `size` — reusing a freed block doesn't grow committed space.
+        CURRENT_SPACE.with(|current_space| current_space.set(current_space.get() + committed));
 
         // Insert new object at the head of the GC list
         let gc_next = GC_HEAD.with(|gc_next| gc_next.replace(NonNull::new(pointer)));
 
+        // Finalizable classes are registered once at module init (see
+        // `finalize::register_finalizer`); every instance is marked here so
+        // `gc::sweep` knows to resurrect it for one cycle instead of
+        // reclaiming it outright the first time it goes unreached.
+        let gc_flags = if finalize::finalizer_for(prototype).is_some() {
+            OBJECT_FLAG_FINALIZABLE
+        } else {
+            0
+        };
+
         // Initialize object metadata
         let object = Object {
             prototype,
-            gc_is_marked: 0,
+            gc_count: 0,
             gc_next,
+            gc_forward: None,
+            gc_flags,
         };
 
-        // If object is not an array, write Object struct directly
+        // If object is not an array, write the Object header directly and
+        // leave the attribute region uninitialized for codegen to fill in.
         if (*prototype).size >= 0 {
             pointer.write(object);
         } else {
-            // For arrays, wrap in ArrayObject
+            // For arrays, wrap in ArrayObject and zero only the element
+            // payload, so a GC triggered mid-initialization of a list never
+            // follows an uninitialized reference slot.
             let object = ArrayObject { object, len };
             (pointer as *mut ArrayObject).write(object);
+            let element_bytes = (-(*prototype).size as u64 * len) as usize;
+            core::ptr::write_bytes(
+                (pointer as *mut u8).add(size_of::<ArrayObject>()),
+                0,
+                element_bytes,
+            );
         }
 
         pointer
@@ -140,6 +212,7 @@ pub unsafe extern "C" fn len(pointer: *mut Object) -> i32 {
 ///
 /// # Safety
 /// - `pointer` must be valid and initialized.
+#[cfg(feature = "std")]
 #[unsafe(export_name = "$print")]
 pub unsafe extern "C" fn print(pointer: *mut Object) -> *mut u8 {
     unsafe {
@@ -179,11 +252,62 @@ pub unsafe extern "C" fn print(pointer: *mut Object) -> *mut u8 {
     }
 }
 
+/// Prints a TypePy object through the embedder's [`platform::Platform`].
+/// Supports int, bool, and str types.
+///
+/// # Safety
+/// - `pointer` must be valid and initialized.
+/// - `platform::install` must have been called.
+#[cfg(not(feature = "std"))]
+#[unsafe(export_name = "$print")]
+pub unsafe extern "C" fn print(pointer: *mut Object) -> *mut u8 {
+    use core::fmt::Write;
+
+    unsafe {
+        if pointer.is_null() {
+            invalid_arg();
+        }
+        let prototype = (*pointer).prototype;
+        match (*prototype).type_tag {
+            Type::Int => {
+                let mut line: alloc_crate::string::String = alloc_crate::string::String::new();
+                let _ = write!(line, "{}", *(pointer.offset(1) as *const i32));
+                platform::platform().write_bytes(line.as_bytes());
+                platform::platform().write_bytes(b"\n");
+            }
+            Type::Bool => {
+                let text = if *(pointer.offset(1) as *const bool) {
+                    "True"
+                } else {
+                    "False"
+                };
+                platform::platform().write_bytes(text.as_bytes());
+                platform::platform().write_bytes(b"\n");
+            }
+            Type::Str => {
+                let object = pointer as *mut ArrayObject;
+                let slice = core::slice::from_raw_parts(
+                    object.offset(1) as *const u8,
+                    (*object).len as usize,
+                );
+                platform::platform().write_bytes(slice);
+                platform::platform().write_bytes(b"\n");
+            }
+            _ => {
+                invalid_arg();
+            }
+        }
+
+        core::ptr::null_mut()
+    }
+}
+
 /// Reads a line from stdin into a new str object.
 ///
 /// # Safety
 /// - `init` must be called.
 /// - `rbp` and `rsp` must describe a valid stack frame.
+#[cfg(feature = "std")]
 #[unsafe(export_name = "$input")]
 pub unsafe extern "C" fn input(rbp: *const u64, rsp: *const u64) -> *mut Object {
     unsafe {
@@ -207,6 +331,35 @@ pub unsafe extern "C" fn input(rbp: *const u64, rsp: *const u64) -> *mut Object
     }
 }
 
+/// Reads a line through the embedder's [`platform::Platform`] into a new
+/// str object.
+///
+/// # Safety
+/// - `init` must be called.
+/// - `rbp` and `rsp` must describe a valid stack frame.
+/// - `platform::install` must have been called.
+#[cfg(not(feature = "std"))]
+#[unsafe(export_name = "$input")]
+pub unsafe extern "C" fn input(rbp: *const u64, rsp: *const u64) -> *mut Object {
+    unsafe {
+        // The embedder has no notion of "arbitrarily long line", so reads
+        // land in a fixed scratch buffer; this mirrors the line-oriented
+        // contract of `$input` without assuming a growable stdin buffer.
+        let mut buffer = [0u8; 4096];
+        let read = platform::platform().read_line(&mut buffer);
+        let input = &buffer[..read];
+
+        let str_proto = INIT_PARAM.with(|init_param| (*init_param.get()).str_prototype);
+        let pointer = alloc_obj(str_proto, input.len() as u64, rbp, rsp);
+        core::ptr::copy_nonoverlapping(
+            input.as_ptr(),
+            (pointer as *mut u8).add(size_of::<ArrayObject>()),
+            input.len(),
+        );
+        pointer
+    }
+}
+
 /// Sets up runtime with initial parameters.
 ///
 /// # Safety
@@ -217,45 +370,78 @@ pub unsafe extern "C" fn init(init_param: *const InitParam) {
 }
 
 /// Aborts the program with a fatal error message.
+#[cfg(feature = "std")]
 pub(crate) fn fatal(message: &str) -> ! {
     eprintln!("Fatal error: {}", message);
     abort();
 }
 
 /// Terminates the program with a given exit code.
+#[cfg(feature = "std")]
 fn exit_code(code: i32) -> ! {
     println!("Exited with error code {}", code);
     exit(code);
 }
 
 /// Signals a runtime type or argument error.
+#[cfg(feature = "std")]
 fn invalid_arg() -> ! {
     println!("Invalid argument");
     exit_code(1)
 }
 
+/// Signals a runtime type or argument error.
+#[cfg(not(feature = "std"))]
+fn invalid_arg() -> ! {
+    platform::platform().trap(1)
+}
+
 /// Runtime trap: division by zero.
+#[cfg(feature = "std")]
 #[unsafe(export_name = "$div_zero")]
 pub extern "C" fn div_zero() -> ! {
     println!("Division by zero");
     exit_code(2)
 }
 
+/// Runtime trap: division by zero.
+#[cfg(not(feature = "std"))]
+#[unsafe(export_name = "$div_zero")]
+pub extern "C" fn div_zero() -> ! {
+    platform::platform().trap(2)
+}
+
 /// Runtime trap: index out of bounds.
+#[cfg(feature = "std")]
 #[unsafe(export_name = "$out_of_bound")]
 pub extern "C" fn out_of_bound() -> ! {
     println!("Index out of bounds");
     exit_code(3)
 }
 
+/// Runtime trap: index out of bounds.
+#[cfg(not(feature = "std"))]
+#[unsafe(export_name = "$out_of_bound")]
+pub extern "C" fn out_of_bound() -> ! {
+    platform::platform().trap(3)
+}
+
 /// Runtime trap: operation on None.
+#[cfg(feature = "std")]
 #[unsafe(export_name = "$none_op")]
 pub extern "C" fn none_op() -> ! {
     println!("Operation on None");
     exit_code(4)
 }
 
-#[cfg(not(test))]
+/// Runtime trap: operation on None.
+#[cfg(not(feature = "std"))]
+#[unsafe(export_name = "$none_op")]
+pub extern "C" fn none_op() -> ! {
+    platform::platform().trap(4)
+}
+
+#[cfg(all(not(test), feature = "std"))]
 pub mod crt0_glue {
     unsafe extern "C" {
         #[link_name = "$typepy_main"]
@@ -1,4 +1,4 @@
-use std::ptr::*;
+use core::ptr::*;
 
 pub const POINTER_SIZE: u32 = 8;
 pub const FUNCTION_POINTER_SIZE: u32 = 8;
@@ -24,29 +24,71 @@ pub struct Prototype {
     // This is a pointer to a bitmap representing the member variables of the object,
     // if the nth position is 1, then the nth member variable is a reference to another object.
     pub reference_bitmap: *const u8,
+    // A second bitmap, parallel to `reference_bitmap` (null if the class
+    // declares no weak fields): if the nth position is 1, the nth member
+    // variable holds a weak reference. A weak field is never also set in
+    // `reference_bitmap`, so the ordinary child-scan in `gc::for_each_child_slot`
+    // already skips it as non-tracing; this bitmap is how `gc::for_each_weak_slot`
+    // locates it anyway, to null it (see `gc::sweep`) once its referent dies,
+    // or to redirect it (see `compact::compact`) once its referent moves.
+    // A finalizer, in contrast, isn't a per-instance field at all — it's
+    // registered once per class via `finalize::register_finalizer`, since
+    // (like the method pointers above) its slot sits after this fixed
+    // header at an offset that varies with the class's method count.
+    pub weak_bitmap: *const u8,
     // ... Object method pointers (right after header in memory).
 }
 pub const PROTOTYPE_SIZE_OFFSET: u32 = 0;
 pub const PROTOTYPE_TAG_OFFSET: u32 = PROTOTYPE_SIZE_OFFSET + 4;
 pub const PROTOTYPE_MAP_OFFSET: u32 = PROTOTYPE_TAG_OFFSET + 4;
-pub const PROTOTYPE_INIT_OFFSET: u32 = PROTOTYPE_MAP_OFFSET + FUNCTION_POINTER_SIZE;
+pub const PROTOTYPE_WEAK_MAP_OFFSET: u32 = PROTOTYPE_MAP_OFFSET + FUNCTION_POINTER_SIZE;
+pub const PROTOTYPE_INIT_OFFSET: u32 = PROTOTYPE_WEAK_MAP_OFFSET + FUNCTION_POINTER_SIZE;
 pub const OBJECT_PROTOTYPE_SIZE: u32 = PROTOTYPE_INIT_OFFSET + FUNCTION_POINTER_SIZE;
-pub const NUM_PROTOTYPE_HEADERS: u32 = 3;
+pub const NUM_PROTOTYPE_HEADERS: u32 = 4;
 
 #[repr(C)] // Makes sure the struct is not reordered by the Rust compiler.
 #[allow(dead_code)] // Used in GC.
 pub struct Object {
     pub prototype: *const Prototype,
-    pub gc_is_marked: u8, // In mark and sweep, represents if this object is marked for usage.
+    // Dual-purpose word: under the incremental tri-color collector (see
+    // `gc::perform_incremental_gc`) this holds a white/gray/black mark
+    // state; under the reference-counting collection mode (see the
+    // `refcount` module) it holds the live count. A single program is
+    // compiled for one mode, never both.
+    pub gc_count: u64,
     pub gc_next: Option<NonNull<Object>>, // A pointer to the next allocated object in the heap, forming a singly linked list of all heap-allocated, GC-managed objects.
+    // Forwarding pointer used by the mark-compact collector (see
+    // `compact::compact`): during the forwarding pass every live object's
+    // post-compaction address is recorded here so the fix-up pass can
+    // redirect references before anything physically moves. `None` from
+    // allocation until the object's first compaction pass; afterwards it
+    // keeps the object's own post-move address until the next pass
+    // overwrites it.
+    pub gc_forward: Option<NonNull<Object>>,
+    // Flags region, orthogonal to `gc_count`: metadata set once at
+    // allocation (`OBJECT_FLAG_FINALIZABLE`, from the class's
+    // `finalize::register_finalizer` registration) or during sweep
+    // (`OBJECT_FLAG_FINALIZATION_PENDING`, once this object has already
+    // been resurrected for one cycle to run its finalizer).
+    pub gc_flags: u64,
     // ... Object attributes (right after header in memory).
 }
 
 pub const OBJECT_PROTOTYPE_OFFSET: u32 = 0;
 pub const OBJECT_GC_COUNT_OFFSET: u32 = OBJECT_PROTOTYPE_OFFSET + 8;
 pub const OBJECT_GC_NEXT_OFFSET: u32 = OBJECT_GC_COUNT_OFFSET + 8;
-pub const OBJECT_ATTRIBUTE_OFFSET: u32 = OBJECT_GC_NEXT_OFFSET + 8;
-pub const NUM_OBJECT_HEADERS: u32 = 3;
+pub const OBJECT_GC_FORWARD_OFFSET: u32 = OBJECT_GC_NEXT_OFFSET + 8;
+pub const OBJECT_GC_FLAGS_OFFSET: u32 = OBJECT_GC_FORWARD_OFFSET + 8;
+pub const OBJECT_ATTRIBUTE_OFFSET: u32 = OBJECT_GC_FLAGS_OFFSET + 8;
+pub const NUM_OBJECT_HEADERS: u32 = 5;
+
+/// Set at allocation if the object's class has a registered finalizer (see
+/// `finalize::register_finalizer`); consulted by `gc::sweep`.
+pub const OBJECT_FLAG_FINALIZABLE: u64 = 1 << 0;
+/// Set by `gc::sweep` the first time a finalizable object goes unreached,
+/// after its finalizer has been enqueued — marks that the next time it goes
+/// unreached it should actually be reclaimed, not resurrected again.
+pub const OBJECT_FLAG_FINALIZATION_PENDING: u64 = 1 << 1;
 
 #[repr(C)] // Makes sure the struct is not reordered by the Rust compiler.
 #[allow(dead_code)] // Used in GC.
@@ -73,4 +115,4 @@ pub const GLOBAL_SECTION_OFFSET: u32 = BOTTOM_FRAME_OFFSET + POINTER_SIZE;
 pub const GLOBAL_SIZE_OFFSET: u32 = GLOBAL_SECTION_OFFSET + POINTER_SIZE;
 pub const GLOBAL_MAP_OFFSET: u32 = GLOBAL_SIZE_OFFSET + 8;
 pub const STR_PROTOTYPE_OFFSET: u32 = GLOBAL_MAP_OFFSET + POINTER_SIZE;
-pub const INIT_PARAM_SIZE: u32 = std::mem::size_of::<InitParam>() as u32;
+pub const INIT_PARAM_SIZE: u32 = core::mem::size_of::<InitParam>() as u32;
@@ -0,0 +1,68 @@
+//! Pluggable embedder hooks used by the `no_std` build (`--no-default-features`).
+//! Under the default `std` feature none of this is consulted: `$print`,
+//! `$input`, and the trap exports keep calling straight into `std` exactly
+//! as before this module existed.
+
+use crate::cell::StaticCell;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+
+/// Syscall shims a freestanding embedder supplies in place of a hosted OS.
+/// Installed once via [`install`] before any `$print`/`$input`/trap export
+/// or allocation runs.
+pub trait Platform: Sync {
+    /// Writes raw bytes to the platform's output sink (e.g. a UART or a
+    /// semihosting channel).
+    fn write_bytes(&self, bytes: &[u8]);
+    /// Reads a line (excluding the trailing `\n`/`\r\n`) into `buf`,
+    /// returning the number of bytes written. Mirrors the hosted
+    /// `$input`'s contract.
+    fn read_line(&self, buf: &mut [u8]) -> usize;
+    /// Terminates the program in response to a runtime trap (the `no_std`
+    /// analogue of `process::abort`/`process::exit`). Never returns.
+    fn trap(&self, code: i32) -> !;
+    /// The allocator backing every TypePy object allocation. Plain
+    /// `no_std` code still needs *some* heap, so rather than assume a
+    /// global `#[global_allocator]` is registered, the embedder hands one
+    /// in explicitly here.
+    fn allocator(&self) -> &dyn GlobalAlloc;
+}
+
+static PLATFORM: StaticCell<Cell<Option<&'static dyn Platform>>> = StaticCell::new(Cell::new(None));
+
+/// Registers the embedder's platform shim. Must run before any other
+/// runtime export in a `no_std` build.
+pub fn install(platform: &'static dyn Platform) {
+    PLATFORM.with(|cell| cell.set(Some(platform)));
+}
+
+pub(crate) fn platform() -> &'static dyn Platform {
+    PLATFORM
+        .with(|cell| cell.get())
+        .expect("platform::install must run before any runtime export")
+}
+
+/// Allocates `size` uninitialized bytes at `align` through the embedder's
+/// allocator, taking the place of `Box`'s implicit use of the global
+/// allocator in the hosted build.
+///
+/// # Safety
+/// - The returned pointer must be freed with [`dealloc_raw`] using the
+///   same `size`/`align`.
+pub(crate) unsafe fn alloc_raw(size: usize, align: usize) -> *mut u8 {
+    unsafe {
+        let layout = Layout::from_size_align(size, align).expect("invalid allocation layout");
+        platform().allocator().alloc(layout)
+    }
+}
+
+/// Frees a pointer obtained from [`alloc_raw`].
+///
+/// # Safety
+/// - `pointer`, `size`, and `align` must match a prior `alloc_raw` call.
+pub(crate) unsafe fn dealloc_raw(pointer: *mut u8, size: usize, align: usize) {
+    unsafe {
+        let layout = Layout::from_size_align(size, align).expect("invalid allocation layout");
+        platform().allocator().dealloc(pointer, layout);
+    }
+}
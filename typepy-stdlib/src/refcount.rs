@@ -0,0 +1,469 @@
+use super::*;
+use alloc_crate::collections::{BTreeMap, BTreeSet};
+
+/// Alternate collection strategy to the incremental tri-color collector in
+/// `gc.rs`: deferred reference counting with a Bacon–Rajan synchronous
+/// cycle collector. A program is compiled for exactly one mode, selected by
+/// building this crate with (or without) the `refcount` feature; under this
+/// mode codegen emits `$retain`/`$release` at assignments instead of
+/// relying on `gc::perform_incremental_gc` safepoints, and `Object::gc_count`
+/// (see `object.rs`) holds the live reference count rather than a mark
+/// state. `alloc_obj`'s call into `gc::perform_incremental_gc` is itself
+/// `#[cfg(not(feature = "refcount"))]` for exactly this reason — it would
+/// otherwise eventually treat a live refcount as a mark state and collect
+/// reachable objects out from under the mutator.
+///
+/// `$alloc_obj` always hands back a freshly allocated object with
+/// `gc_count == 0`; under this mode codegen must immediately `$retain` it
+/// to acquire the first owning reference, mirroring how the mark-and-sweep
+/// mode starts every object unmarked.
+// Number of buffered candidates that triggers a trial deletion pass.
+const TRIAL_DELETION_THRESHOLD: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Black, // In use, or proven reachable from outside the candidate set.
+    Gray,  // Reached during trial deletion; internal references not yet confirmed.
+    White, // Proven unreachable except through candidate cycles; collectible.
+}
+
+// Objects whose count was decremented but not freed — possible roots of a
+// reference cycle, awaiting a trial deletion pass.
+static CANDIDATE_BUFFER: StaticCell<RefCell<Vec<NonNull<Object>>>> =
+    StaticCell::new(RefCell::new(Vec::new()));
+// Dedupes `CANDIDATE_BUFFER`: an object is buffered at most once.
+static BUFFERED: StaticCell<RefCell<BTreeSet<usize>>> = StaticCell::new(RefCell::new(BTreeSet::new()));
+// Objects whose real count reached zero via `release` while still sitting
+// in `CANDIDATE_BUFFER`. Freeing one immediately would leave a dangling
+// `NonNull` behind in that `Vec` for `collect_cycles` to later dereference,
+// so `release` defers the actual free to `collect_cycles`, which drains
+// (and so stops referencing) the buffer first — mirroring the canonical
+// Bacon–Rajan `Release`, which only frees immediately when the object isn't
+// buffered.
+static PENDING_FREE: StaticCell<RefCell<BTreeSet<usize>>> = StaticCell::new(RefCell::new(BTreeSet::new()));
+// Trial-deletion color, keyed by object address. Absent means black (the
+// default state outside of a collection pass); cleared after every pass so
+// colors never leak between runs.
+static COLORS: StaticCell<RefCell<BTreeMap<usize, Color>>> =
+    StaticCell::new(RefCell::new(BTreeMap::new()));
+
+fn color_of(pointer: *mut Object) -> Color {
+    COLORS.with(|colors| {
+        colors
+            .borrow()
+            .get(&(pointer as usize))
+            .copied()
+            .unwrap_or(Color::Black)
+    })
+}
+
+fn set_color(pointer: *mut Object, color: Color) {
+    COLORS.with(|colors| {
+        colors.borrow_mut().insert(pointer as usize, color);
+    });
+}
+
+fn unbuffer(pointer: *mut Object) {
+    BUFFERED.with(|buffered| buffered.borrow_mut().remove(&(pointer as usize)));
+}
+
+fn is_buffered(pointer: *mut Object) -> bool {
+    BUFFERED.with(|buffered| buffered.borrow().contains(&(pointer as usize)))
+}
+
+fn mark_pending_free(pointer: *mut Object) {
+    PENDING_FREE.with(|pending| {
+        pending.borrow_mut().insert(pointer as usize);
+    });
+}
+
+// Runs `pointer`'s finalizer, if it has one. Must be called before a
+// dying object's children are released/freed (see `release`/`collect_white`)
+// and before its own memory is reclaimed (see `free_object`), so it only
+// ever observes valid memory — unlike `gc::sweep`, this mode never needs to
+// resurrect the object for that: a real refcount of zero is unconditionally
+// final, so there's exactly one chance to finalize and it's right now.
+unsafe fn run_finalizer(pointer: *mut Object) {
+    unsafe {
+        if let Some(finalizer) = finalize::finalizer_for((*pointer).prototype) {
+            finalizer(pointer);
+        }
+    }
+}
+
+/// Visits every reference field of `object_ptr`, using the same
+/// `reference_bitmap`/`ObjList` traversal as `gc::scan_children`.
+unsafe fn for_each_child(object_ptr: *mut Object, mut visit: impl FnMut(*mut Object)) {
+    unsafe {
+        let handle = handle::ObjHandle::new(object_ptr).expect("object_ptr checked non-null by callers");
+        match (*handle.prototype()).type_tag {
+            Type::Other => {
+                let field_count = ((*handle.prototype()).size / 8) as usize;
+                let ref_bitmap = (*handle.prototype()).reference_bitmap;
+                for i in 0..field_count {
+                    let flag = *ref_bitmap.add(i / 8) & (1 << (i % 8));
+                    if flag != 0 {
+                        let child = *handle.attribute_ptr(i) as *mut Object;
+                        if !child.is_null() {
+                            visit(child);
+                        }
+                    }
+                }
+            }
+            Type::ObjList => {
+                let array = handle::ArrayHandle::new(object_ptr).expect("ObjList is always array-shaped");
+                for i in 0..array.array_len() {
+                    let child = *(array.array_element_ptr(i as usize, 8) as *const u64) as *mut Object;
+                    if !child.is_null() {
+                        visit(child);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Removes `pointer` from the `GC_HEAD` singly-linked list (`lib.rs`'s
+// `alloc_obj` links every object there in cons order regardless of
+// collection mode, but nothing under this mode ever unlinks one) and, in
+// the same walk, nulls any live weak slot pointing at it — mirroring
+// `gc::sweep`'s weak-slot pass, since under this mode `free_object` is the
+// only place an object actually dies.
+//
+// Unlinking can stop as soon as `pointer` is found, but the weak-slot scan
+// must not: `GC_HEAD` is LIFO (newest object at the head), so an object
+// further down the list was allocated *before* `pointer` and may still
+// hold a weak reference to it — stopping the scan there would leave that
+// reference dangling instead of nulled.
+unsafe fn unlink_gc_head(pointer: *mut Object) {
+    unsafe {
+        let mut head = GC_HEAD.with(|gc_head| gc_head.get());
+        let mut cursor = &mut head;
+        let mut unlinked = false;
+        while let Some(object) = *cursor {
+            let object_ptr = object.as_ptr();
+            if !unlinked && object_ptr == pointer {
+                *cursor = (*object_ptr).gc_next;
+                unlinked = true;
+                continue;
+            }
+            gc::for_each_weak_slot(object_ptr, |slot| {
+                if *slot == pointer as u64 {
+                    *(slot as *mut u64) = 0;
+                }
+            });
+            cursor = &mut (*object_ptr).gc_next;
+        }
+        GC_HEAD.with(|gc_head| gc_head.set(head));
+    }
+}
+
+/// Reclaims an object's storage through the slab allocator, matching
+/// `gc::sweep`'s reclaim path: unlinks it from `GC_HEAD` and nulls any live
+/// weak slot pointing at it, then hands its memory back to the allocator.
+///
+/// Callers — `release`, and `collect_white` for cyclic garbage — are
+/// responsible for having already run `pointer`'s finalizer (via
+/// `run_finalizer`) and released/collected its children *before* calling
+/// this, not after: by the time this runs, `pointer` is about to become
+/// invalid memory.
+unsafe fn free_object(pointer: *mut Object) {
+    unsafe {
+        unlink_gc_head(pointer);
+        let size_units =
+            calculate_size((*pointer).prototype, || (*(pointer as *mut ArrayObject)).len);
+        let released = alloc::free_units(pointer as *mut AllocUnit, size_units);
+        CURRENT_SPACE.with(|current_space| current_space.set(current_space.get() - released));
+    }
+}
+
+/// Increments an object's reference count. A no-op on a null pointer, so
+/// callers can `$retain` a possibly-`None` slot unconditionally.
+///
+/// # Safety
+/// - `pointer`, if non-null, must be a live object allocated by `$alloc_obj`.
+#[unsafe(export_name = "$retain")]
+pub unsafe extern "C" fn retain(pointer: *mut Object) -> *mut Object {
+    unsafe {
+        if !pointer.is_null() {
+            (*pointer).gc_count += 1;
+        }
+        pointer
+    }
+}
+
+/// Decrements an object's reference count, freeing it on reaching zero
+/// (running its finalizer, if any, then recursively releasing its children
+/// — in that order, since nothing else can legally reach them once the
+/// last owning reference is dropped, but the finalizer must still find
+/// every field valid). A surviving release past zero buffers the object as
+/// a cycle candidate, and running past the threshold triggers a trial
+/// deletion pass.
+///
+/// # Safety
+/// - `pointer`, if non-null, must be a live object allocated by `$alloc_obj`
+///   that has been `$retain`ed at least once more than it has been
+///   `$release`d.
+#[unsafe(export_name = "$release")]
+pub unsafe extern "C" fn release(pointer: *mut Object) {
+    unsafe {
+        if pointer.is_null() {
+            return;
+        }
+        (*pointer).gc_count -= 1;
+        if (*pointer).gc_count == 0 {
+            run_finalizer(pointer);
+            for_each_child(pointer, |child| release(child));
+            if is_buffered(pointer) {
+                mark_pending_free(pointer);
+            } else {
+                free_object(pointer);
+            }
+        } else {
+            buffer_candidate(pointer);
+        }
+    }
+}
+
+fn buffer_candidate(pointer: *mut Object) {
+    let newly_buffered = BUFFERED.with(|buffered| buffered.borrow_mut().insert(pointer as usize));
+    if !newly_buffered {
+        return;
+    }
+    let len = CANDIDATE_BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.push(NonNull::new(pointer).expect("pointer checked non-null by release"));
+        buffer.len()
+    });
+    if len >= TRIAL_DELETION_THRESHOLD {
+        unsafe { collect_cycles() };
+    }
+}
+
+/// Runs one Bacon–Rajan trial deletion pass over the buffered candidates:
+/// (1) tentatively decrement and gray everything reachable via internal
+/// edges, (2) anything a surviving count proves externally reachable is
+/// restored and blackened, (3) whatever is left gray is cyclic garbage and
+/// is freed.
+unsafe fn collect_cycles() {
+    unsafe {
+        let candidates: Vec<NonNull<Object>> =
+            CANDIDATE_BUFFER.with(|buffer| buffer.borrow_mut().drain(..).collect());
+        BUFFERED.with(|buffered| buffered.borrow_mut().clear());
+
+        // Anything `release` already drove to a real count of zero is
+        // definite garbage, not a cycle suspect — free it directly instead
+        // of running trial deletion over already-dead memory.
+        let pending_free = PENDING_FREE.with(|pending| core::mem::take(&mut *pending.borrow_mut()));
+        let candidates: Vec<NonNull<Object>> = candidates
+            .into_iter()
+            .filter(|candidate| {
+                if pending_free.contains(&(candidate.as_ptr() as usize)) {
+                    free_object(candidate.as_ptr());
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+
+        for candidate in &candidates {
+            mark_gray(candidate.as_ptr());
+        }
+        for candidate in &candidates {
+            scan(candidate.as_ptr());
+        }
+        for candidate in &candidates {
+            collect_white(candidate.as_ptr());
+        }
+
+        // Colors are only meaningful mid-pass; reset for the next one.
+        COLORS.with(|colors| colors.borrow_mut().clear());
+    }
+}
+
+unsafe fn mark_gray(pointer: *mut Object) {
+    unsafe {
+        if color_of(pointer) == Color::Gray {
+            return;
+        }
+        set_color(pointer, Color::Gray);
+        for_each_child(pointer, |child| {
+            (*child).gc_count -= 1;
+            mark_gray(child);
+        });
+    }
+}
+
+unsafe fn scan(pointer: *mut Object) {
+    unsafe {
+        if color_of(pointer) != Color::Gray {
+            return;
+        }
+        if (*pointer).gc_count > 0 {
+            scan_black(pointer);
+        } else {
+            set_color(pointer, Color::White);
+            for_each_child(pointer, |child| scan(child));
+        }
+    }
+}
+
+// An external reference proves `pointer`'s whole subgraph reachable:
+// restore the counts trial deletion tentatively removed and blacken it.
+unsafe fn scan_black(pointer: *mut Object) {
+    unsafe {
+        set_color(pointer, Color::Black);
+        for_each_child(pointer, |child| {
+            (*child).gc_count += 1;
+            if color_of(child) != Color::Black {
+                scan_black(child);
+            }
+        });
+    }
+}
+
+unsafe fn collect_white(pointer: *mut Object) {
+    unsafe {
+        if color_of(pointer) != Color::White {
+            return;
+        }
+        // Blacken before recursing so a shared white child is only freed once.
+        set_color(pointer, Color::Black);
+        // Finalize before touching any child: same ordering requirement as
+        // `release` above, since a child reachable only from `pointer` is
+        // about to be collected too and the finalizer must still see it.
+        run_finalizer(pointer);
+        for_each_child(pointer, |child| collect_white(child));
+        unbuffer(pointer);
+        free_object(pointer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::Mutex;
+
+    // `StaticCell` (see `cell.rs`) is `Sync` only because the compiled
+    // runtime it backs never runs on more than one thread; `cargo test`'s
+    // default thread-per-test parallelism would violate that for every
+    // static this module touches (`GC_HEAD`, `CANDIDATE_BUFFER`, `COLORS`,
+    // `alloc.rs`'s slab free lists, ...), so tests here serialize on this
+    // lock instead of relying on `--test-threads=1`.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    // Bit 0 set: field 0 is a traced reference.
+    const ONE_REF_FIELD: [u8; 1] = [0b0000_0001];
+
+    // Leaks a `Prototype` with a stable address for the rest of the test's
+    // lifetime, matching how a compiled program's class metadata is itself
+    // permanently live.
+    fn leak_prototype(size: i32, reference_bitmap: *const u8) -> *const Prototype {
+        Box::leak(Box::new(Prototype {
+            size,
+            type_tag: Type::Other,
+            reference_bitmap,
+            weak_bitmap: core::ptr::null(),
+        })) as *const Prototype
+    }
+
+    // Whether `pointer` is still linked into `GC_HEAD`. Only ever compares
+    // addresses, so it's safe to call with a pointer that's already been
+    // freed (it just won't be found).
+    fn gc_head_contains(pointer: *mut Object) -> bool {
+        let mut cursor = GC_HEAD.with(|gc_head| gc_head.get());
+        while let Some(object) = cursor {
+            if object.as_ptr() == pointer {
+                return true;
+            }
+            cursor = unsafe { (*object.as_ptr()).gc_next };
+        }
+        false
+    }
+
+    #[test]
+    fn reference_cycle_is_reclaimed() {
+        let _guard = TEST_LOCK.lock().unwrap();
+        unsafe {
+            let prototype = leak_prototype(8, ONE_REF_FIELD.as_ptr());
+
+            let a = alloc_obj(prototype, 0, core::ptr::null(), core::ptr::null());
+            let b = alloc_obj(prototype, 0, core::ptr::null(), core::ptr::null());
+            retain(a);
+            retain(b);
+
+            // a.field0 = b, b.field0 = a: a non-trivial two-object cycle,
+            // each holding the other via an owning (retained) reference.
+            let handle_a = handle::ObjHandle::new(a).unwrap();
+            let handle_b = handle::ObjHandle::new(b).unwrap();
+            *handle_a.attribute_ptr(0) = b as u64;
+            retain(b);
+            *handle_b.attribute_ptr(0) = a as u64;
+            retain(a);
+
+            // Drop the test's own references; the only thing keeping `a`
+            // and `b` alive now is the cycle between them.
+            release(a);
+            release(b);
+            assert!(gc_head_contains(a), "cycle members must survive until collected");
+            assert!(gc_head_contains(b), "cycle members must survive until collected");
+
+            collect_cycles();
+
+            assert!(!gc_head_contains(a), "cyclic garbage must be reclaimed");
+            assert!(!gc_head_contains(b), "cyclic garbage must be reclaimed");
+        }
+    }
+
+    static FINALIZER_CHILD_PROTOTYPE: AtomicPtr<Prototype> = AtomicPtr::new(core::ptr::null_mut());
+    static FINALIZER_SAW_LIVE_CHILD: AtomicBool = AtomicBool::new(false);
+
+    // Reads the still-referenced child's prototype pointer and compares it
+    // against what it was set to at allocation time. `free_object` hands
+    // freed memory to `alloc::free_units`, which splices it onto a
+    // size-class free list by overwriting its first word (the same offset
+    // as `Object::prototype`) — so this comparison reliably fails if the
+    // child was freed before this finalizer ran.
+    unsafe extern "C" fn check_child_is_live(object: *mut Object) {
+        unsafe {
+            let handle = handle::ObjHandle::new(object).expect("object checked non-null by release");
+            let child = *handle.attribute_ptr(0) as *mut Object;
+            let live = !child.is_null()
+                && (*child).prototype as *mut Prototype == FINALIZER_CHILD_PROTOTYPE.load(Ordering::SeqCst);
+            FINALIZER_SAW_LIVE_CHILD.store(live, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn finalizer_runs_before_child_is_freed() {
+        let _guard = TEST_LOCK.lock().unwrap();
+        FINALIZER_SAW_LIVE_CHILD.store(false, Ordering::SeqCst);
+        unsafe {
+            let child_prototype = leak_prototype(0, core::ptr::null());
+            let parent_prototype = leak_prototype(8, ONE_REF_FIELD.as_ptr());
+            FINALIZER_CHILD_PROTOTYPE.store(child_prototype as *mut Prototype, Ordering::SeqCst);
+            finalize::register_finalizer(parent_prototype, check_child_is_live);
+
+            let parent = alloc_obj(parent_prototype, 0, core::ptr::null(), core::ptr::null());
+            retain(parent);
+            let child = alloc_obj(child_prototype, 0, core::ptr::null(), core::ptr::null());
+
+            let handle = handle::ObjHandle::new(parent).unwrap();
+            *handle.attribute_ptr(0) = child as u64;
+            retain(child);
+
+            // Drops the test's only reference to `parent`, running its
+            // finalizer. Without the ordering fix above, `release` would
+            // free `child` first and `check_child_is_live` would observe a
+            // dangling pointer instead.
+            release(parent);
+
+            assert!(FINALIZER_SAW_LIVE_CHILD.load(Ordering::SeqCst));
+            assert!(!gc_head_contains(parent));
+            assert!(!gc_head_contains(child));
+        }
+    }
+}